@@ -21,7 +21,16 @@ mod packets {
 	const DELIM_ARRAY: u8 = 4;
 	const DELIM_OBJECT: u8 = 5;
 	const DELIM_INT: u8 = 6;
-	const MAX_DELIM: u8 = DELIM_INT;
+	/// Wraps a deflated encoding of another packet. The body is `length`
+	/// (a VQL varint) followed by that many zlib-compressed bytes; inflating
+	/// them yields the inner packet's normal wire encoding.
+	const DELIM_COMPRESSED: u8 = 7;
+	const MAX_DELIM: u8 = DELIM_COMPRESSED;
+
+	/// Refuses to inflate a `DELIM_COMPRESSED` packet past this many bytes,
+	/// so a malicious or corrupt peer can't use a small compressed payload
+	/// to force an enormous allocation (a decompression bomb).
+	const MAX_INFLATED_SIZE: usize = 64 * 1024 * 1024;
 
 	mod vql {
 		use std::io;
@@ -61,6 +70,37 @@ mod packets {
 
 			writer.write_all(&buf[..size]).await
 		}
+
+		/// Like `write_vql`, but appends directly to an in-memory buffer
+		/// instead of going through an `AsyncWrite`, since encoding bytes
+		/// is a purely synchronous operation with no actual I/O to await.
+		pub fn write_vql_sync(value: i32, out: &mut Vec<u8>) {
+			if value == 0 {
+				out.push(0);
+				return;
+			}
+
+			// re-declare as u32 so the right shift is logical, not arithmetic
+			let mut value = {
+				let bytes = value.to_be_bytes();
+				u32::from_be_bytes(bytes)
+			};
+
+			let mut buf: [u8; MAX_LENGTH] = [0; MAX_LENGTH];
+			let mut size = 0;
+			while value != 0 {
+				buf[size] = (value & 0b01111111) as u8;
+				value >>= 7;
+				if value > 0 {
+					buf[size] |= 0b10000000;
+				}
+
+				size += 1;
+			}
+
+			out.extend_from_slice(&buf[..size]);
+		}
+
 		/// Encodes a variable-length quantity into a byte array. Must have length >= 5
 		pub async fn read_vql(reader: impl tokio::io::AsyncRead) -> io::Result<i32> {
 			pin!(reader);
@@ -89,6 +129,21 @@ mod packets {
 			unreachable!();
 		}
 
+		/// Like `read_vql`, but works on an in-memory slice and returns `None`
+		/// rather than erroring when `buf` does not yet contain a full varint,
+		/// so callers can buffer more bytes and retry.
+		pub fn try_read_vql(buf: &[u8]) -> Option<(i32, usize)> {
+			let mut value: i32 = 0;
+			for (n, &byte) in buf.iter().enumerate() {
+				value |= ((byte & 0b0111_1111) as i32) << (n * 7);
+				if byte & 0b1000_0000 == 0 {
+					return Some((value, n + 1));
+				}
+			}
+
+			None
+		}
+
 		mod tests {
 			use super::*;
 			use std::io::Cursor;
@@ -102,7 +157,72 @@ mod packets {
 					assert_eq!(read_vql(&mut rw).await.unwrap(), input);
 				}
 			}
+
+			#[tokio::test]
+			async fn test_write_vql_sync_matches_write_vql() {
+				for input in vec![0, 1, -1, 1234, -1234, i32::MAX, i32::MIN] {
+					let mut rw = Cursor::new(Vec::new());
+					write_vql(input, &mut rw).await.unwrap();
+
+					let mut sync_out = Vec::new();
+					write_vql_sync(input, &mut sync_out);
+
+					assert_eq!(sync_out, rw.into_inner());
+				}
+			}
+
+			#[test]
+			fn test_try_read_vql_partial() {
+				let mut rw = Cursor::new(Vec::new());
+				futures::executor::block_on(write_vql(i32::MIN, &mut rw)).unwrap();
+				let full = rw.into_inner();
+
+				for n in 0..full.len() {
+					assert_eq!(try_read_vql(&full[..n]), None);
+				}
+				assert_eq!(
+					try_read_vql(&full),
+					Some((i32::MIN, full.len()))
+				);
+			}
+		}
+	}
+
+	/// Options controlling how a `CodePacket` is serialized. Passed through
+	/// `to_writer_with_options` so they apply uniformly to nested packets.
+	#[derive(Debug, Clone, Copy, Default)]
+	pub struct SerializeOptions {
+		/// Buffer/VSBuffer/Object bodies larger than this many bytes are
+		/// deflated and wrapped in a `DELIM_COMPRESSED` packet. `None`
+		/// (the default) never compresses, matching plain `to_writer`.
+		pub compression_threshold: Option<usize>,
+	}
+
+	fn deflate(data: &[u8]) -> io::Result<Vec<u8>> {
+		use flate2::{write::ZlibEncoder, Compression};
+		use std::io::Write;
+
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(data)?;
+		encoder.finish()
+	}
+
+	fn inflate(data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+		use flate2::read::ZlibDecoder;
+		use std::io::Read;
+
+		let mut out = Vec::new();
+		ZlibDecoder::new(data)
+			.take(max_size as u64 + 1)
+			.read_to_end(&mut out)?;
+		if out.len() > max_size {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("decompressed packet exceeds the {} byte limit", max_size),
+			));
 		}
+
+		Ok(out)
 	}
 
 	#[derive(Debug, PartialEq, Eq)]
@@ -177,6 +297,20 @@ mod packets {
 				} else if typ == DELIM_INT {
 					let v = vql::read_vql(&mut reader).await?;
 					Self::Int32(v)
+				} else if typ == DELIM_COMPRESSED {
+					let clen = vql::read_vql(&mut reader).await? as usize;
+					let mut compressed = vec![0; clen];
+					reader.read_exact(&mut compressed).await?;
+					let inflated = inflate(&compressed, MAX_INFLATED_SIZE)?;
+					match Self::try_decode(&inflated)? {
+						Some((packet, _)) => packet,
+						None => {
+							return Err(io::Error::new(
+								io::ErrorKind::InvalidData,
+								"compressed packet did not contain a complete inner packet",
+							))
+						}
+					}
 				} else if typ > MAX_DELIM {
 					return Err(io::Error::new(
 						io::ErrorKind::InvalidData,
@@ -225,46 +359,149 @@ mod packets {
 			}
 		}
 
+		/// Attempts to decode a single packet from the front of `buf` without
+		/// consuming any input. Returns the packet along with the number of
+		/// bytes it occupied on success, or `None` if `buf` does not yet hold
+		/// a complete packet (including a partial varint or a partially
+		/// filled nested array), so the caller can buffer more data and
+		/// retry. Mirrors `from_reader`, but over an in-memory slice instead
+		/// of an `AsyncRead`.
+		pub(crate) fn try_decode(buf: &[u8]) -> io::Result<Option<(Self, usize)>> {
+			let mut pos = 0;
+			let mut nested_reads: Vec<(usize, Vec<CodePacket>)> = vec![(0, Vec::with_capacity(1))];
+
+			loop {
+				let typ = match buf.get(pos) {
+					Some(&t) => t,
+					None => return Ok(None),
+				};
+				let mut next_pos = pos + 1;
+
+				let next_packet = if typ == DELIM_UNDEFINED {
+					Self::Undefined
+				} else if typ == DELIM_INT {
+					let (v, n) = match vql::try_read_vql(&buf[next_pos..]) {
+						Some(r) => r,
+						None => return Ok(None),
+					};
+					next_pos += n;
+					Self::Int32(v)
+				} else if typ == DELIM_COMPRESSED {
+					let (clen, n) = match vql::try_read_vql(&buf[next_pos..]) {
+						Some(r) => r,
+						None => return Ok(None),
+					};
+					next_pos += n;
+					let clen = clen as usize;
+
+					if buf.len() < next_pos + clen {
+						return Ok(None);
+					}
+					let inflated = inflate(&buf[next_pos..next_pos + clen], MAX_INFLATED_SIZE)?;
+					next_pos += clen;
+
+					match Self::try_decode(&inflated)? {
+						Some((packet, _)) => packet,
+						None => {
+							return Err(io::Error::new(
+								io::ErrorKind::InvalidData,
+								"compressed packet did not contain a complete inner packet",
+							))
+						}
+					}
+				} else if typ > MAX_DELIM {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidData,
+						format!("unexpected packet type {}", typ),
+					));
+				} else {
+					let (len, n) = match vql::try_read_vql(&buf[next_pos..]) {
+						Some(r) => r,
+						None => return Ok(None),
+					};
+					next_pos += n;
+					let len = len as usize;
+
+					if typ == DELIM_ARRAY {
+						Self::Array(Vec::with_capacity(len))
+					} else {
+						if buf.len() < next_pos + len {
+							return Ok(None);
+						}
+						let data = buf[next_pos..next_pos + len].to_vec();
+						next_pos += len;
+						match typ {
+							DELIM_STRING => Self::String(String::from_utf8(data).map_err(|e| {
+								io::Error::new(
+									io::ErrorKind::InvalidData,
+									format!("invalid utf-8 in string: {}", e),
+								)
+							})?),
+							DELIM_BUFFER => Self::Buffer(data),
+							DELIM_VSBUFFER => Self::VSBuffer(data),
+							DELIM_OBJECT => Self::Object(data),
+							_ => unreachable!(),
+						}
+					}
+				};
+
+				pos = next_pos;
+
+				if let Self::Array(a) = next_packet {
+					nested_reads.push((nested_reads.last().unwrap().1.len(), a));
+				} else {
+					nested_reads.last_mut().unwrap().1.push(next_packet);
+					let last = nested_reads.last().unwrap();
+					if last.1.capacity() == last.1.len() {
+						let (i, mut arr) = nested_reads.pop().unwrap();
+						if nested_reads.is_empty() {
+							return Ok(Some((arr.remove(0), pos)));
+						} else {
+							nested_reads
+								.last_mut()
+								.unwrap()
+								.1
+								.insert(i, Self::Array(arr));
+						}
+					}
+				}
+			}
+		}
+
 		/// Encodes the CodePacket to the given writer. It's recommended to use a
-		/// BufWriter when calling this method.
-		#[async_recursion]
+		/// BufWriter when calling this method. Never compresses; use
+		/// `to_writer_with_options` to opt into that.
 		pub async fn to_writer(&self, writer: impl tokio::io::AsyncWrite + Send) -> io::Result<()> {
+			self.to_writer_with_options(writer, SerializeOptions::default())
+				.await
+		}
+
+		/// Like `to_writer`, but compresses `Buffer`/`VSBuffer`/`Object`
+		/// bodies larger than `options.compression_threshold` into a
+		/// `DELIM_COMPRESSED` wrapper.
+		#[async_recursion]
+		pub async fn to_writer_with_options(
+			&self,
+			writer: impl tokio::io::AsyncWrite + Send,
+			options: SerializeOptions,
+		) -> io::Result<()> {
 			pin!(writer);
 			match self {
 				CodePacket::Undefined => writer.write_all(&[DELIM_UNDEFINED]).await,
 				CodePacket::String(s) => {
-					writer
-						.write_all(&make_sized_header(DELIM_STRING, s.len()))
-						.await?;
+					write_sized_header(DELIM_STRING, s.len(), &mut writer).await?;
 					writer.write_all(s.as_bytes()).await
 				}
-				CodePacket::Buffer(b) => {
-					writer
-						.write_all(&make_sized_header(DELIM_BUFFER, b.len()))
-						.await?;
-					writer.write_all(&b).await
-				}
-				CodePacket::VSBuffer(b) => {
-					writer
-						.write_all(&make_sized_header(DELIM_VSBUFFER, b.len()))
-						.await?;
-					writer.write_all(&b).await
-				}
+				CodePacket::Buffer(b) => write_body(DELIM_BUFFER, b, &mut writer, options).await,
+				CodePacket::VSBuffer(b) => write_body(DELIM_VSBUFFER, b, &mut writer, options).await,
 				CodePacket::Array(p) => {
-					writer
-						.write_all(&make_sized_header(DELIM_ARRAY, p.len()))
-						.await?;
+					write_sized_header(DELIM_ARRAY, p.len(), &mut writer).await?;
 					for cp in p {
-						cp.to_writer(&mut writer).await?;
+						cp.to_writer_with_options(&mut writer, options).await?;
 					}
 					Ok(())
 				}
-				CodePacket::Object(b) => {
-					writer
-						.write_all(&make_sized_header(DELIM_OBJECT, b.len()))
-						.await?;
-					writer.write_all(&b).await
-				}
+				CodePacket::Object(b) => write_body(DELIM_OBJECT, b, &mut writer, options).await,
 				CodePacket::Int32(i) => {
 					writer.write_all(&[DELIM_INT]).await?;
 					vql::write_vql(*i, &mut writer).await
@@ -272,6 +509,40 @@ mod packets {
 			}
 		}
 
+		/// Synchronous sibling of `to_writer_with_options`, appending the
+		/// encoded bytes directly to `out` instead of going through an
+		/// `AsyncWrite`. Used by `RpcCodec`'s `Encoder` impl, which has no
+		/// way to `.await` inside `tokio_util::codec::Encoder::encode`'s
+		/// synchronous signature.
+		pub(crate) fn encode_sync(&self, options: SerializeOptions, out: &mut Vec<u8>) -> io::Result<()> {
+			match self {
+				CodePacket::Undefined => {
+					out.push(DELIM_UNDEFINED);
+					Ok(())
+				}
+				CodePacket::String(s) => {
+					write_sized_header_sync(DELIM_STRING, s.len(), out);
+					out.extend_from_slice(s.as_bytes());
+					Ok(())
+				}
+				CodePacket::Buffer(b) => write_body_sync(DELIM_BUFFER, b, out, options),
+				CodePacket::VSBuffer(b) => write_body_sync(DELIM_VSBUFFER, b, out, options),
+				CodePacket::Array(p) => {
+					write_sized_header_sync(DELIM_ARRAY, p.len(), out);
+					for cp in p {
+						cp.encode_sync(options, out)?;
+					}
+					Ok(())
+				}
+				CodePacket::Object(b) => write_body_sync(DELIM_OBJECT, b, out, options),
+				CodePacket::Int32(i) => {
+					out.push(DELIM_INT);
+					vql::write_vql_sync(*i, out);
+					Ok(())
+				}
+			}
+		}
+
 		/// Returns a JSON representation of the CodePacket. Buffers are encoded
 		/// using numerical arrays.
 		pub fn to_json(&self) -> Vec<u8> {
@@ -298,6 +569,9 @@ mod packets {
 			}
 		}
 
+		/// Reads this packet as an `i32`, accepting both the native
+		/// `CodePacket::Int32` varint encoding and, for compatibility with
+		/// older peers, an object packet carrying a JSON-encoded integer.
 		pub fn to_i32(&self) -> Result<i32, io::Error> {
 			if let CodePacket::Int32(i) = self {
 				return Ok(*i);
@@ -331,67 +605,251 @@ mod packets {
 		}
 	}
 
-	fn make_sized_header(type_marker: u8, size: usize) -> [u8; 5] {
-		let l = (size as u32).to_be_bytes();
-		[type_marker, l[0], l[1], l[2], l[3]]
+	/// Writes the `type_marker` byte followed by `size` encoded as a VQL
+	/// varint, matching the length prefix `from_reader` expects for every
+	/// non-`Undefined`/`Int32` packet kind.
+	async fn write_sized_header(
+		type_marker: u8,
+		size: usize,
+		mut writer: impl tokio::io::AsyncWrite + Unpin,
+	) -> io::Result<()> {
+		writer.write_all(&[type_marker]).await?;
+		vql::write_vql(size as i32, &mut writer).await
+	}
+
+	/// Writes a `Buffer`/`VSBuffer`/`Object` body, deflating it behind a
+	/// `DELIM_COMPRESSED` wrapper when it exceeds
+	/// `options.compression_threshold`.
+	async fn write_body(
+		type_marker: u8,
+		data: &[u8],
+		mut writer: impl tokio::io::AsyncWrite + Unpin + Send,
+		options: SerializeOptions,
+	) -> io::Result<()> {
+		if let Some(threshold) = options.compression_threshold {
+			if data.len() > threshold {
+				let mut inner = Vec::new();
+				write_sized_header(type_marker, data.len(), &mut inner).await?;
+				inner.extend_from_slice(data);
+
+				let compressed = deflate(&inner)?;
+				writer.write_all(&[DELIM_COMPRESSED]).await?;
+				vql::write_vql(compressed.len() as i32, &mut writer).await?;
+				return writer.write_all(&compressed).await;
+			}
+		}
+
+		write_sized_header(type_marker, data.len(), &mut writer).await?;
+		writer.write_all(data).await
+	}
+
+	/// Synchronous sibling of `write_sized_header`, appending to `out`
+	/// directly instead of going through an `AsyncWrite`.
+	fn write_sized_header_sync(type_marker: u8, size: usize, out: &mut Vec<u8>) {
+		out.push(type_marker);
+		vql::write_vql_sync(size as i32, out);
+	}
+
+	/// Synchronous sibling of `write_body`, appending to `out` directly
+	/// instead of going through an `AsyncWrite`.
+	fn write_body_sync(
+		type_marker: u8,
+		data: &[u8],
+		out: &mut Vec<u8>,
+		options: SerializeOptions,
+	) -> io::Result<()> {
+		if let Some(threshold) = options.compression_threshold {
+			if data.len() > threshold {
+				let mut inner = Vec::new();
+				write_sized_header_sync(type_marker, data.len(), &mut inner);
+				inner.extend_from_slice(data);
+
+				let compressed = deflate(&inner)?;
+				out.push(DELIM_COMPRESSED);
+				vql::write_vql_sync(compressed.len() as i32, out);
+				out.extend_from_slice(&compressed);
+				return Ok(());
+			}
+		}
+
+		write_sized_header_sync(type_marker, data.len(), out);
+		out.extend_from_slice(data);
+		Ok(())
+	}
+
+	/// Refuses to read a single stream chunk larger than this, so a
+	/// corrupt or hostile chunk-length prefix can't force an unbounded
+	/// allocation.
+	pub const MAX_STREAM_CHUNK_SIZE: usize = 1024 * 1024;
+
+	/// Pipes `source` out as a sequence of length-delimited chunks (each a
+	/// VQL-encoded length followed by that many bytes, capped at
+	/// `MAX_STREAM_CHUNK_SIZE`), terminated by a zero-length chunk. This is
+	/// a sibling to `CodePacket`'s in-memory `Buffer`/`Object` bodies for
+	/// payloads too large to hold entirely in RAM on either side - e.g. a
+	/// file transferred alongside a request.
+	pub async fn write_stream_body(
+		mut source: impl tokio::io::AsyncRead + Unpin,
+		mut writer: impl tokio::io::AsyncWrite + Unpin,
+	) -> io::Result<()> {
+		let mut chunk = vec![0u8; MAX_STREAM_CHUNK_SIZE];
+		loop {
+			let n = source.read(&mut chunk).await?;
+			vql::write_vql(n as i32, &mut writer).await?;
+			if n == 0 {
+				return Ok(());
+			}
+			writer.write_all(&chunk[..n]).await?;
+		}
+	}
+
+	/// Reads a single chunk written by `write_stream_body`: `None` once the
+	/// terminating zero-length chunk is seen.
+	async fn read_stream_chunk(
+		mut reader: impl tokio::io::AsyncRead + Unpin,
+	) -> io::Result<Option<bytes::Bytes>> {
+		let len = vql::read_vql(&mut reader).await? as usize;
+		if len == 0 {
+			return Ok(None);
+		}
+		if len > MAX_STREAM_CHUNK_SIZE {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!(
+					"stream chunk of {} bytes exceeds the {} byte cap",
+					len, MAX_STREAM_CHUNK_SIZE
+				),
+			));
+		}
+
+		let mut buf = vec![0; len];
+		reader.read_exact(&mut buf).await?;
+		Ok(Some(bytes::Bytes::from(buf)))
+	}
+
+	/// Returns a `Stream` over the chunks `write_stream_body` wrote to the
+	/// other end of `reader`, pulling each one off the wire lazily so the
+	/// full payload is never buffered at once.
+	pub fn read_stream_body(
+		reader: impl tokio::io::AsyncRead + Unpin + Send + 'static,
+	) -> impl futures::Stream<Item = io::Result<bytes::Bytes>> {
+		futures::stream::unfold(Some(reader), |state| async move {
+			let mut reader = state?;
+			match read_stream_chunk(&mut reader).await {
+				Ok(Some(chunk)) => Some((Ok(chunk), Some(reader))),
+				Ok(None) => None,
+				Err(e) => Some((Err(e), None)),
+			}
+		})
+	}
+
+	mod tests {
+		use super::*;
+		use std::io::Cursor;
+
+		#[tokio::test]
+		async fn test_round_trips_without_compression_by_default() {
+			let packet = CodePacket::from_buffer(vec![1; 4096]);
+
+			let mut buf = Vec::new();
+			packet.to_writer(&mut buf).await.unwrap();
+			assert_eq!(buf[0], DELIM_BUFFER);
+
+			let mut cursor = Cursor::new(buf);
+			assert_eq!(CodePacket::from_reader(&mut cursor).await.unwrap(), packet);
+		}
+
+		#[tokio::test]
+		async fn test_compresses_large_bodies_above_threshold() {
+			let packet = CodePacket::from_buffer(vec![7; 4096]);
+
+			let mut compressed = Vec::new();
+			packet
+				.to_writer_with_options(
+					&mut compressed,
+					SerializeOptions {
+						compression_threshold: Some(1024),
+					},
+				)
+				.await
+				.unwrap();
+			assert_eq!(compressed[0], DELIM_COMPRESSED);
+			assert!(compressed.len() < 4096);
+
+			let mut cursor = Cursor::new(compressed);
+			assert_eq!(CodePacket::from_reader(&mut cursor).await.unwrap(), packet);
+
+			let mut small = Vec::new();
+			CodePacket::from_buffer(vec![7; 10])
+				.to_writer_with_options(
+					&mut small,
+					SerializeOptions {
+						compression_threshold: Some(1024),
+					},
+				)
+				.await
+				.unwrap();
+			assert_eq!(small[0], DELIM_BUFFER);
+		}
+
+		#[tokio::test]
+		async fn test_stream_body_round_trips_across_chunk_boundaries() {
+			use futures::StreamExt;
+
+			let payload = vec![9u8; MAX_STREAM_CHUNK_SIZE + 10];
+
+			let mut wire = Vec::new();
+			write_stream_body(Cursor::new(payload.clone()), &mut wire)
+				.await
+				.unwrap();
+
+			let chunks: Vec<_> = read_stream_body(Cursor::new(wire))
+				.collect::<Vec<_>>()
+				.await
+				.into_iter()
+				.collect::<io::Result<Vec<_>>>()
+				.unwrap();
+
+			assert_eq!(chunks.len(), 2);
+			let received: Vec<u8> = chunks.into_iter().flatten().collect();
+			assert_eq!(received, payload);
+		}
+
+		#[tokio::test]
+		async fn test_read_stream_body_rejects_oversized_chunk() {
+			use futures::StreamExt;
+
+			let mut wire = Vec::new();
+			vql::write_vql((MAX_STREAM_CHUNK_SIZE + 1) as i32, &mut wire)
+				.await
+				.unwrap();
+
+			let mut chunks = read_stream_body(Cursor::new(wire));
+			assert!(chunks.next().await.unwrap().is_err());
+		}
 	}
 }
 
 mod requests {
 	use serde::{Deserialize, Serialize};
 
-	use super::packets::CodePacket;
+	use super::packets::{CodePacket, SerializeOptions};
 	use std::{fmt::Debug, io};
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 	const REQUEST_PROMISE: u8 = 100;
 	const REQUEST_PROMISE_CANCEL: u8 = 101;
 	const REQUEST_EVENT_LISTEN: u8 = 102;
 	const REQUEST_EVENT_DISPOSE: u8 = 103;
+	const REQUEST_PROMISE_STREAM: u8 = 104;
 
 	const RESPONSE_INITIALIZE: u8 = 200;
 	const RESPONSE_PROMISE_SUCCESS: u8 = 201;
 	const RESPONSE_PROMISE_ERROR: u8 = 202;
 	const RESPONSE_PROMISE_ERROR_OBJ: u8 = 203;
 	const RESPONSE_EVENT_FIRE: u8 = 204;
-
-	#[derive(Debug, PartialEq, Eq)]
-	pub enum Message {
-		RequestPromise {
-			id: i32,
-			channel_name: String,
-			name: String,
-			arg: CodePacket,
-		},
-		RequestPromiseCancel {
-			id: i32,
-		},
-		RequestEventListen {
-			id: i32,
-			channel_name: String,
-			name: String,
-			arg: CodePacket,
-		},
-		RequestEventDispose {
-			id: i32,
-		},
-
-		ResponseInitialize,
-		ResponsePromiseSuccess {
-			id: i32,
-		},
-		ResponsePromiseError {
-			id: i32,
-			data: PromiseErrorData,
-		},
-		ResponsePromiseErrorObject {
-			id: i32,
-			data: CodePacket,
-		},
-		ResponseEventFired {
-			id: i32,
-			data: CodePacket,
-		},
-	}
+	const RESPONSE_PROMISE_STREAM_SUCCESS: u8 = 205;
+	const RESPONSE_EVENT_ERROR: u8 = 206;
 
 	#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
 	pub struct PromiseErrorData {
@@ -407,159 +865,729 @@ mod requests {
 		))
 	}
 
-	pub async fn read_message(
-		mut reader: impl tokio::io::AsyncRead + Unpin,
-	) -> Result<Message, io::Error> {
-		let header = CodePacket::from_reader(&mut reader).await?;
-		let header = match header {
-			CodePacket::Array(parts) => parts,
-			a => return invalid("expected header to be array", a),
+	/// Resolves a header field's wire kind (`Int32`/`Str`) to the Rust type
+	/// its value is stored as on [`Message`].
+	macro_rules! header_field_ty {
+		(Int32) => {
+			i32
+		};
+		(Str) => {
+			String
 		};
+	}
 
-		// todo: integers are encoded as strings, but varints would be more efficient
-		let typ = match header.get(0) {
-			Some(p) => p.to_i32()?,
-			a => return invalid("expected packet type", a),
+	/// Packs a header field's value into the `CodePacket` the leading
+	/// header array carries it as.
+	macro_rules! header_field_encode {
+		($val:expr, Int32) => {
+			CodePacket::Int32(*$val)
+		};
+		($val:expr, Str) => {
+			CodePacket::String($val.clone())
 		};
+	}
 
-		let m = match typ as u8 {
-			REQUEST_PROMISE => Message::RequestPromise {
-				id: match header.get(1) {
-					Some(CodePacket::Int32(e)) => *e,
-					u => return invalid("expected request id", u),
-				},
-				channel_name: match header.get(2) {
-					Some(CodePacket::String(s)) => s.clone(),
-					u => return invalid("expected channel name", u),
-				},
-				name: match header.get(3) {
-					Some(CodePacket::String(s)) => s.clone(),
-					u => return invalid("expected request name", u),
-				},
-				arg: match CodePacket::from_reader(&mut reader).await {
-					Ok(o) => o,
-					u => return invalid("expected promise body", u),
-				},
-			},
-			REQUEST_EVENT_LISTEN => Message::RequestEventListen {
-				id: match header.get(1) {
-					Some(CodePacket::Int32(e)) => *e,
-					u => return invalid("expected request id", u),
-				},
-				channel_name: match header.get(2) {
-					Some(CodePacket::String(s)) => s.clone(),
-					u => return invalid("expected channel name", u),
-				},
-				name: match header.get(3) {
-					Some(CodePacket::String(s)) => s.clone(),
-					u => return invalid("expected request name", u),
-				},
-				arg: match CodePacket::from_reader(&mut reader).await {
-					Ok(o) => o,
-					u => return invalid("expected promise body", u),
-				},
-			},
-			REQUEST_PROMISE_CANCEL => Message::RequestPromiseCancel {
-				id: match header.get(1) {
-					Some(CodePacket::Int32(e)) => *e,
-					u => return invalid("expected request id", u),
-				},
-			},
-			REQUEST_EVENT_DISPOSE => Message::RequestEventDispose {
-				id: match header.get(1) {
-					Some(CodePacket::Int32(e)) => *e,
-					u => return invalid("expected request id", u),
-				},
-			},
-			RESPONSE_INITIALIZE => Message::ResponseInitialize,
-			RESPONSE_PROMISE_SUCCESS => Message::ResponsePromiseSuccess {
-				id: match header.get(1) {
-					Some(CodePacket::Int32(e)) => *e,
-					u => return invalid("expected request id", u),
-				},
-			},
-			RESPONSE_PROMISE_ERROR => Message::ResponsePromiseError {
-				id: match header.get(1) {
-					Some(CodePacket::Int32(e)) => *e,
-					u => return invalid("expected request id", u),
-				},
-				data: match CodePacket::from_reader(&mut reader).await {
-					Ok(o) => o.to_object()?,
-					u => return invalid("expected promise body", u),
-				},
-			},
-			RESPONSE_PROMISE_ERROR_OBJ => Message::ResponsePromiseErrorObject {
-				id: match header.get(1) {
-					Some(CodePacket::Int32(e)) => *e,
-					u => return invalid("expected request id", u),
-				},
-				data: match CodePacket::from_reader(&mut reader).await {
-					Ok(o) => o,
-					u => return invalid("expected promise body", u),
-				},
-			},
-			RESPONSE_EVENT_FIRE => Message::ResponseEventFired {
-				id: match header.get(1) {
-					Some(CodePacket::Int32(e)) => *e,
-					u => return invalid("expected request id", u),
-				},
-				data: match CodePacket::from_reader(&mut reader).await {
-					Ok(o) => o,
-					u => return invalid("expected promise body", u),
-				},
-			},
-			t => {
-				return Err(io::Error::new(
-					io::ErrorKind::InvalidData,
-					format!("unknown packet type {}", t),
-				))
+	/// Extracts and validates header field `$idx` out of an already-parsed
+	/// header array, using the same "invalid packet" error for every field.
+	///
+	/// The `Int32` arm is where every header id gained tolerance for both
+	/// encodings: it routes through `CodePacket::to_i32`, which accepts the
+	/// native `CodePacket::Int32` varint as well as the legacy
+	/// object-encoded form older peers sent. Before this macro existed, the
+	/// hand-written `read_message`/`try_decode_message` arms read header
+	/// ids with a match on `CodePacket::Int32` only; consolidating them
+	/// here is what broadened every header field to accept both forms at
+	/// once, not just the one the test below exercises.
+	macro_rules! header_field_decode {
+		($header:expr, $idx:expr, Int32, $label:expr) => {
+			match $header.get($idx) {
+				Some(p) => p.to_i32()?,
+				u => return invalid($label, u),
+			}
+		};
+		($header:expr, $idx:expr, Str, $label:expr) => {
+			match $header.get($idx) {
+				Some(CodePacket::String(s)) => s.clone(),
+				u => return invalid($label, u),
 			}
 		};
-
-		Ok(m)
 	}
 
-	mod tests {
+	/// Resolves a trailing body field's wire kind to the Rust type its
+	/// value is stored as: `Body` keeps the raw `CodePacket`, `Json(T)`
+	/// deserializes the body as an object packet.
+	macro_rules! body_field_ty {
+		(Body) => {
+			CodePacket
+		};
+		(Json($bty:ty)) => {
+			$bty
+		};
+	}
 
-		use std::io::Cursor;
+	/// Writes a trailing body field, encoding `Json(T)` fields as an object
+	/// packet first.
+	macro_rules! body_field_encode {
+		($val:expr, Body, $writer:expr) => {
+			$val.to_writer($writer).await
+		};
+		($val:expr, Json($bty:ty), $writer:expr) => {
+			CodePacket::from_object($val)
+				.map_err(|e| {
+					io::Error::new(io::ErrorKind::InvalidData, format!("failed to encode json: {}", e))
+				})?
+				.to_writer($writer)
+				.await
+		};
+	}
 
-		use super::*;
+	/// Synchronous sibling of `body_field_encode!`, used by
+	/// `write_message_sync`.
+	macro_rules! body_field_encode_sync {
+		($val:expr, Body, $out:expr) => {
+			$val.encode_sync(SerializeOptions::default(), $out)
+		};
+		($val:expr, Json($bty:ty), $out:expr) => {
+			CodePacket::from_object($val)
+				.map_err(|e| {
+					io::Error::new(io::ErrorKind::InvalidData, format!("failed to encode json: {}", e))
+				})?
+				.encode_sync(SerializeOptions::default(), $out)
+		};
+	}
 
-		#[tokio::test]
-		async fn test_parses_request() {
-			let input = vec![
-				4, 4, 6, 100, 6, 0, 1, 11, 116, 101, 115, 116, 99, 104, 97, 110, 110, 101, 108, 1,
-				5, 109, 97, 114, 99, 111, 0,
-			];
+	/// Reads a trailing body field from an `AsyncRead`, used by
+	/// `read_message`.
+	macro_rules! body_field_decode_async {
+		(Body, $reader:expr) => {
+			match CodePacket::from_reader($reader).await {
+				Ok(o) => o,
+				u => return invalid("expected message body", u),
+			}
+		};
+		(Json($bty:ty), $reader:expr) => {
+			match CodePacket::from_reader($reader).await {
+				Ok(o) => o.to_object()?,
+				u => return invalid("expected message body", u),
+			}
+		};
+	}
 
-			let actual = read_message(Cursor::new(input)).await.unwrap();
+	/// Converts an already-decoded trailing body packet (`$next`, typically
+	/// `next_body!()`) into a body field's value, used by
+	/// `try_decode_message`.
+	macro_rules! body_field_decode_sync {
+		(Body, $next:expr) => {
+			$next
+		};
+		(Json($bty:ty), $next:expr) => {
+			$next.to_object()?
+		};
+	}
 
-			assert_eq!(
-				actual,
-				Message::RequestPromise {
-					id: 0,
-					channel_name: "testchannel".to_string(),
-					name: "marco".to_string(),
-					arg: CodePacket::Undefined,
+	/// Declares the `Message` enum together with its wire codec, so adding a
+	/// new request/response kind is a few declarative lines instead of a
+	/// new hand-written match arm in each of `read_message`,
+	/// `try_decode_message`, and `write_message`.
+	///
+	/// Each variant names its numeric type tag and an ordered list of
+	/// header fields (`slot: name: kind`, packed into the leading
+	/// `CodePacket::Array` at `slot`), plus an optional trailing `body`
+	/// field read via `CodePacket::from_reader`/`try_decode`.
+	macro_rules! define_messages {
+		(
+			$(
+				$variant:ident ( $tag:ident ) {
+					$( $hidx:literal : $hfield:ident : $hkind:ident ),* $(,)?
+					$( ; body $bfield:ident : $bkind:ident $( ( $bty:ty ) )? )?
+				}
+			),* $(,)?
+		) => {
+			#[derive(Debug, PartialEq, Eq)]
+			pub enum Message {
+				$(
+					$variant {
+						$( $hfield: header_field_ty!($hkind), )*
+						$( $bfield: body_field_ty!($bkind $(($bty))?), )?
+					},
+				)*
+			}
+
+			pub async fn read_message(
+				mut reader: impl tokio::io::AsyncRead + Unpin,
+			) -> Result<Message, io::Error> {
+				let header = CodePacket::from_reader(&mut reader).await?;
+				let header = match header {
+					CodePacket::Array(parts) => parts,
+					a => return invalid("expected header to be array", a),
+				};
+
+				let typ = match header.get(0) {
+					Some(p) => p.to_i32()?,
+					a => return invalid("expected packet type", a),
+				};
+
+				let m = match typ as u8 {
+					$(
+						$tag => Message::$variant {
+							$( $hfield: header_field_decode!(header, $hidx, $hkind, concat!("expected ", stringify!($hfield))), )*
+							$( $bfield: body_field_decode_async!($bkind $(($bty))?, &mut reader), )?
+						},
+					)*
+					t => {
+						return Err(io::Error::new(
+							io::ErrorKind::InvalidData,
+							format!("unknown packet type {}", t),
+						))
+					}
+				};
+
+				Ok(m)
+			}
+
+			/// Attempts to decode a single `Message` from the front of `buf`
+			/// without consuming any input, returning the message and the
+			/// number of bytes it occupied, or `None` if `buf` does not yet
+			/// hold a complete message. Shares its field-by-field
+			/// validation with `read_message`.
+			fn try_decode_message(buf: &[u8]) -> io::Result<Option<(Message, usize)>> {
+				let (header, mut pos) = match CodePacket::try_decode(buf)? {
+					Some(r) => r,
+					None => return Ok(None),
+				};
+				let header = match header {
+					CodePacket::Array(parts) => parts,
+					a => return invalid("expected header to be array", a),
+				};
+
+				let typ = match header.get(0) {
+					Some(p) => p.to_i32()?,
+					a => return invalid("expected packet type", a),
+				};
+
+				macro_rules! next_body {
+					() => {{
+						match CodePacket::try_decode(&buf[pos..])? {
+							Some((packet, n)) => {
+								pos += n;
+								packet
+							}
+							None => return Ok(None),
+						}
+					}};
+				}
+
+				let m = match typ as u8 {
+					$(
+						$tag => Message::$variant {
+							$( $hfield: header_field_decode!(header, $hidx, $hkind, concat!("expected ", stringify!($hfield))), )*
+							$( $bfield: body_field_decode_sync!($bkind $(($bty))?, next_body!()), )?
+						},
+					)*
+					t => {
+						return Err(io::Error::new(
+							io::ErrorKind::InvalidData,
+							format!("unknown packet type {}", t),
+						))
+					}
+				};
+
+				Ok(Some((m, pos)))
+			}
+
+			/// Serializes a `Message` to the given writer, mirroring the
+			/// layout that `read_message` expects: a header
+			/// `CodePacket::Array` holding the type tag plus any fixed
+			/// fields, followed by a trailing body packet for the message
+			/// kinds that carry one.
+			pub async fn write_message(
+				message: &Message,
+				mut writer: impl tokio::io::AsyncWrite + Unpin + Send,
+			) -> io::Result<()> {
+				match message {
+					$(
+						Message::$variant { $($hfield,)* $($bfield,)? } => {
+							CodePacket::Array(vec![
+								CodePacket::Int32($tag as i32),
+								$( header_field_encode!($hfield, $hkind), )*
+							])
+							.to_writer(&mut writer)
+							.await?;
+
+							#[allow(unused_mut)]
+							let mut result: io::Result<()> = Ok(());
+							$( result = body_field_encode!($bfield, $bkind $(($bty))?, &mut writer); )?
+							result
+						}
+					)*
+				}
+			}
+
+			/// Synchronous sibling of `write_message`, appending directly to
+			/// `out` instead of going through an `AsyncWrite`. Used by
+			/// `RpcCodec`'s `Encoder` impl, which is a synchronous trait
+			/// method with no way to `.await` a real I/O operation.
+			pub fn write_message_sync(message: &Message, out: &mut Vec<u8>) -> io::Result<()> {
+				match message {
+					$(
+						Message::$variant { $($hfield,)* $($bfield,)? } => {
+							CodePacket::Array(vec![
+								CodePacket::Int32($tag as i32),
+								$( header_field_encode!($hfield, $hkind), )*
+							])
+							.encode_sync(SerializeOptions::default(), out)?;
+
+							#[allow(unused_mut)]
+							let mut result: io::Result<()> = Ok(());
+							$( result = body_field_encode_sync!($bfield, $bkind $(($bty))?, out); )?
+							result
+						}
+					)*
+				}
+			}
+		};
+	}
+
+	define_messages! {
+		RequestPromise(REQUEST_PROMISE) {
+			1: id: Int32,
+			2: channel_name: Str,
+			3: name: Str
+			; body arg: Body
+		},
+		RequestEventListen(REQUEST_EVENT_LISTEN) {
+			1: id: Int32,
+			2: channel_name: Str,
+			3: name: Str
+			; body arg: Body
+		},
+		RequestPromiseCancel(REQUEST_PROMISE_CANCEL) {
+			1: id: Int32
+		},
+		RequestEventDispose(REQUEST_EVENT_DISPOSE) {
+			1: id: Int32
+		},
+		ResponseInitialize(RESPONSE_INITIALIZE) {},
+		ResponsePromiseSuccess(RESPONSE_PROMISE_SUCCESS) {
+			1: id: Int32
+		},
+		ResponsePromiseError(RESPONSE_PROMISE_ERROR) {
+			1: id: Int32
+			; body data: Json(PromiseErrorData)
+		},
+		ResponsePromiseErrorObject(RESPONSE_PROMISE_ERROR_OBJ) {
+			1: id: Int32
+			; body data: Body
+		},
+		ResponseEventFired(RESPONSE_EVENT_FIRE) {
+			1: id: Int32
+			; body data: Body
+		},
+		// Sent instead of ever firing `ResponseEventFired` for this `id`,
+		// so a subscription to an unknown channel closes the client's
+		// `EventStream` immediately rather than sitting registered forever
+		// indistinguishable from a valid, merely-quiet one.
+		ResponseEventError(RESPONSE_EVENT_ERROR) {
+			1: id: Int32
+			; body data: Json(PromiseErrorData)
+		},
+		// These two carry no `CodePacket` body field: the argument/result is
+		// too large to hold in memory as one, so it isn't part of the
+		// header at all. A sender pairs one with a `packets::write_stream_body`
+		// call on the same writer right after `write_message` returns; a
+		// receiver that gets one back from `read_message` must likewise
+		// follow up with `packets::read_stream_body` on the same reader to
+		// pull the streamed chunks before reading anything else off it.
+		// `rpc::ChannelClient`/`rpc::ChannelServerBuilder` don't do that
+		// follow-up yet, so their shared read loops treat receiving either
+		// of these the same as a transport error and stop, rather than
+		// misparsing the un-drained stream body as the next message.
+		RequestPromiseStream(REQUEST_PROMISE_STREAM) {
+			1: id: Int32,
+			2: channel_name: Str,
+			3: name: Str
+		},
+		ResponsePromiseStreamSuccess(RESPONSE_PROMISE_STREAM_SUCCESS) {
+			1: id: Int32
+		},
+	}
+
+	/// Refuses to allocate a framed message body larger than this, so a
+	/// corrupt or hostile `length` field can't be used to force an
+	/// unbounded allocation before any data has actually arrived.
+	pub const MESSAGE_LENGTH_MAX: u32 = 64 * 1024 * 1024;
+
+	/// The peer has half-closed this `stream_id`; no further messages will
+	/// be sent on it.
+	pub const FLAG_REMOTE_CLOSED: u8 = 0b0000_0001;
+	/// This frame carries no message body (used for pure control signals,
+	/// e.g. closing a stream without a final message).
+	pub const FLAG_NO_DATA: u8 = 0b0000_0010;
+
+	/// The only frame type produced today. Reserved so a future control
+	/// frame kind (e.g. stream open/close negotiation for the `rpc`
+	/// multiplexer) can be distinguished from a data-carrying frame without
+	/// a wire format change.
+	pub const FRAME_TYPE_DATA: u8 = 0;
+
+	/// Size of the fixed frame header: `length: u32, stream_id: u32, type:
+	/// u8, flags: u8`.
+	const FRAME_HEADER_LEN: usize = 4 + 4 + 1 + 1;
+
+	/// One `write_framed`/`read_framed` frame: a `Message` (unless
+	/// `FLAG_NO_DATA` is set) tagged with the logical stream it belongs to.
+	#[derive(Debug, PartialEq, Eq)]
+	pub struct Frame {
+		pub stream_id: u32,
+		pub typ: u8,
+		pub flags: u8,
+		pub message: Option<Message>,
+	}
+
+	/// Writes `message` prefixed with a fixed header carrying `length`,
+	/// `stream_id`, `typ`, and `flags`, so a single socket can carry several
+	/// logical channels (once demultiplexed by `stream_id`, see
+	/// `rpc::FrameRouter`/`rpc::FrameMultiplexer`) and signal half-close/
+	/// cancellation without a full `RequestPromiseCancel` round-trip. This
+	/// is the framed counterpart to the legacy unframed `write_message`.
+	pub async fn write_framed(
+		stream_id: u32,
+		typ: u8,
+		flags: u8,
+		message: Option<&Message>,
+		mut writer: impl tokio::io::AsyncWrite + Unpin,
+	) -> io::Result<()> {
+		let body = match message {
+			Some(m) => {
+				let mut buf = Vec::new();
+				write_message(m, &mut buf).await?;
+				buf
+			}
+			None => Vec::new(),
+		};
+
+		if body.len() as u64 > MESSAGE_LENGTH_MAX as u64 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!(
+					"framed message of {} bytes exceeds MESSAGE_LENGTH_MAX ({})",
+					body.len(),
+					MESSAGE_LENGTH_MAX
+				),
+			));
+		}
+
+		writer.write_all(&(body.len() as u32).to_be_bytes()).await?;
+		writer.write_all(&stream_id.to_be_bytes()).await?;
+		writer.write_all(&[typ, flags]).await?;
+		writer.write_all(&body).await
+	}
+
+	/// Reads one frame written by `write_framed`, enforcing
+	/// `MESSAGE_LENGTH_MAX` on the declared length before allocating a
+	/// buffer for it.
+	pub async fn read_framed(mut reader: impl tokio::io::AsyncRead + Unpin) -> io::Result<Frame> {
+		let mut header = [0u8; FRAME_HEADER_LEN];
+		reader.read_exact(&mut header).await?;
+
+		let length = u32::from_be_bytes(header[0..4].try_into().unwrap());
+		let stream_id = u32::from_be_bytes(header[4..8].try_into().unwrap());
+		let typ = header[8];
+		let flags = header[9];
+
+		if length > MESSAGE_LENGTH_MAX {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!(
+					"framed message length {} exceeds MESSAGE_LENGTH_MAX ({})",
+					length, MESSAGE_LENGTH_MAX
+				),
+			));
+		}
+
+		let mut body = vec![0u8; length as usize];
+		reader.read_exact(&mut body).await?;
+
+		let message = if flags & FLAG_NO_DATA != 0 || body.is_empty() {
+			None
+		} else {
+			Some(read_message(&mut std::io::Cursor::new(body)).await?)
+		};
+
+		Ok(Frame {
+			stream_id,
+			typ,
+			flags,
+			message,
+		})
+	}
+
+	/// A `tokio_util` codec that frames a byte stream as a sequence of
+	/// [`Message`]s, so a socket can be wrapped in a `Framed` to get a
+	/// `Stream`/`Sink` pair instead of driving `read_message`/`write_message`
+	/// in a hand-written loop.
+	#[derive(Debug, Default)]
+	pub struct RpcCodec;
+
+	impl tokio_util::codec::Decoder for RpcCodec {
+		type Item = Message;
+		type Error = io::Error;
+
+		fn decode(&mut self, src: &mut bytes::BytesMut) -> io::Result<Option<Self::Item>> {
+			match try_decode_message(src)? {
+				Some((message, consumed)) => {
+					bytes::Buf::advance(src, consumed);
+					Ok(Some(message))
+				}
+				None => Ok(None),
+			}
+		}
+	}
+
+	impl tokio_util::codec::Encoder<Message> for RpcCodec {
+		type Error = io::Error;
+
+		fn encode(&mut self, item: Message, dst: &mut bytes::BytesMut) -> io::Result<()> {
+			let mut buf = Vec::new();
+			write_message_sync(&item, &mut buf)?;
+			dst.extend_from_slice(&buf);
+			Ok(())
+		}
+	}
+
+	mod tests {
+
+		use std::io::Cursor;
+
+		use super::*;
+
+		#[tokio::test]
+		async fn test_parses_request() {
+			let input = vec![
+				4, 4, 6, 100, 6, 0, 1, 11, 116, 101, 115, 116, 99, 104, 97, 110, 110, 101, 108, 1,
+				5, 109, 97, 114, 99, 111, 0,
+			];
+
+			let actual = read_message(Cursor::new(input)).await.unwrap();
+
+			assert_eq!(
+				actual,
+				Message::RequestPromise {
+					id: 0,
+					channel_name: "testchannel".to_string(),
+					name: "marco".to_string(),
+					arg: CodePacket::Undefined,
+				}
+			);
+		}
+
+		#[tokio::test]
+		async fn test_codec_round_trips_and_buffers_partial_frames() {
+			use tokio_util::codec::{Decoder, Encoder};
+
+			let message = Message::RequestPromise {
+				id: 42,
+				channel_name: "testchannel".to_string(),
+				name: "marco".to_string(),
+				arg: CodePacket::from_string("polo".to_string()),
+			};
+
+			let mut codec = RpcCodec;
+			let mut encoded = bytes::BytesMut::new();
+			codec.encode(message, &mut encoded).unwrap();
+
+			// a partial frame should not decode, and must not corrupt state
+			// that a later call with the rest of the bytes needs.
+			let mut partial = encoded.split_to(encoded.len() - 1);
+			assert_eq!(codec.decode(&mut partial).unwrap(), None);
+
+			let mut full = partial;
+			full.extend_from_slice(&encoded);
+			let decoded = codec.decode(&mut full).unwrap().unwrap();
+			assert_eq!(
+				decoded,
+				Message::RequestPromise {
+					id: 42,
+					channel_name: "testchannel".to_string(),
+					name: "marco".to_string(),
+					arg: CodePacket::from_string("polo".to_string()),
+				}
+			);
+			assert_eq!(full.len(), 0);
+		}
+
+		#[tokio::test]
+		async fn test_write_message_sync_matches_write_message() {
+			let message = Message::ResponsePromiseError {
+				id: 7,
+				data: PromiseErrorData {
+					message: "boom".to_string(),
+					name: "Error".to_string(),
+					stack: Some(vec!["at foo".to_string()]),
+				},
+			};
+
+			let mut expected = Vec::new();
+			write_message(&message, &mut expected).await.unwrap();
+
+			let mut actual = Vec::new();
+			write_message_sync(&message, &mut actual).unwrap();
+
+			assert_eq!(actual, expected);
+		}
+
+		#[tokio::test]
+		async fn test_write_framed_read_framed_round_trip() {
+			let message = Message::RequestPromiseCancel { id: 7 };
+
+			let mut buf = Vec::new();
+			write_framed(99, FRAME_TYPE_DATA, 0, Some(&message), &mut buf)
+				.await
+				.unwrap();
+
+			let frame = read_framed(Cursor::new(buf)).await.unwrap();
+			assert_eq!(frame.stream_id, 99);
+			assert_eq!(frame.typ, FRAME_TYPE_DATA);
+			assert_eq!(frame.flags, 0);
+			assert_eq!(frame.message, Some(message));
+		}
+
+		#[tokio::test]
+		async fn test_write_framed_no_data_flag_carries_no_message() {
+			let mut buf = Vec::new();
+			write_framed(
+				5,
+				FRAME_TYPE_DATA,
+				FLAG_NO_DATA | FLAG_REMOTE_CLOSED,
+				None,
+				&mut buf,
+			)
+			.await
+			.unwrap();
+
+			let frame = read_framed(Cursor::new(buf)).await.unwrap();
+			assert_eq!(frame.stream_id, 5);
+			assert_eq!(frame.typ, FRAME_TYPE_DATA);
+			assert_eq!(frame.flags, FLAG_NO_DATA | FLAG_REMOTE_CLOSED);
+			assert_eq!(frame.message, None);
+		}
+
+		#[tokio::test]
+		async fn test_read_framed_rejects_length_over_max() {
+			let mut buf = Vec::new();
+			buf.extend_from_slice(&(MESSAGE_LENGTH_MAX + 1).to_be_bytes());
+			buf.extend_from_slice(&0u32.to_be_bytes());
+			buf.extend_from_slice(&[0, 0]);
+
+			assert!(read_framed(Cursor::new(buf)).await.is_err());
+		}
+
+		#[tokio::test]
+		async fn test_request_id_round_trips_as_a_vql_varint_at_boundary_values() {
+			for id in [0, 1, -1, 1234, -1234, i32::MAX, i32::MIN] {
+				let message = Message::RequestPromiseCancel { id };
+
+				let mut buf = Vec::new();
+				write_message(&message, &mut buf).await.unwrap();
+
+				// the id is written natively as a `CodePacket::Int32`, i.e. a
+				// VQL varint, not JSON text.
+				let header = CodePacket::from_reader(&mut Cursor::new(buf.clone()))
+					.await
+					.unwrap();
+				match header {
+					CodePacket::Array(parts) => assert_eq!(parts.get(1), Some(&CodePacket::Int32(id))),
+					other => panic!("expected header array, got {:?}", other),
+				}
+
+				let decoded = read_message(Cursor::new(buf)).await.unwrap();
+				assert_eq!(decoded, message);
+			}
+		}
+
+		#[tokio::test]
+		async fn test_read_message_accepts_legacy_object_encoded_ids() {
+			// older peers encoded header integers as a JSON object packet
+			// rather than a native `CodePacket::Int32`; `read_message` must
+			// still accept that form for one release cycle.
+			let header = CodePacket::Array(vec![
+				CodePacket::Int32(REQUEST_PROMISE_CANCEL as i32),
+				CodePacket::from_object(&42i32).unwrap(),
+			]);
+
+			let mut buf = Vec::new();
+			header.to_writer(&mut buf).await.unwrap();
+
+			let decoded = read_message(Cursor::new(buf)).await.unwrap();
+			assert_eq!(decoded, Message::RequestPromiseCancel { id: 42 });
+		}
+
+		#[tokio::test]
+		async fn test_promise_stream_pairs_its_header_with_a_streamed_body() {
+			use super::super::packets::{read_stream_body, write_stream_body, MAX_STREAM_CHUNK_SIZE};
+			use futures::StreamExt;
+
+			let payload = vec![3u8; MAX_STREAM_CHUNK_SIZE + 1];
+
+			let mut wire = Vec::new();
+			write_message(
+				&Message::RequestPromiseStream {
+					id: 7,
+					channel_name: "files".to_string(),
+					name: "upload".to_string(),
+				},
+				&mut wire,
+			)
+			.await
+			.unwrap();
+			write_stream_body(Cursor::new(payload.clone()), &mut wire)
+				.await
+				.unwrap();
+
+			let mut wire = Cursor::new(wire);
+			let header = read_message(&mut wire).await.unwrap();
+			assert_eq!(
+				header,
+				Message::RequestPromiseStream {
+					id: 7,
+					channel_name: "files".to_string(),
+					name: "upload".to_string(),
 				}
 			);
+
+			let chunks: Vec<_> = read_stream_body(wire)
+				.collect::<Vec<_>>()
+				.await
+				.into_iter()
+				.collect::<io::Result<Vec<_>>>()
+				.unwrap();
+			let received: Vec<u8> = chunks.into_iter().flatten().collect();
+			assert_eq!(received, payload);
 		}
 	}
 }
 
 mod rpc {
-	use super::requests;
+	use super::requests::{self, Message, PromiseErrorData, RpcCodec};
 	use std::{
 		collections::HashMap,
-		sync::atomic::{AtomicI32, Ordering},
+		future::Future,
+		pin::Pin,
+		sync::{
+			atomic::{AtomicI32, Ordering},
+			Arc, Mutex,
+		},
+		task::{Context, Poll},
 	};
 
 	use async_trait::async_trait;
-	use serde::Serialize;
+	use futures::{SinkExt, Stream, StreamExt};
 	use tokio::{
-		io::{AsyncRead, AsyncWrite, BufReader, BufStream, BufWriter},
-		sync::oneshot,
+		io::{AsyncRead, AsyncWrite},
+		sync::{mpsc, oneshot},
+		task::AbortHandle,
 	};
+	use tokio_util::codec::{FramedRead, FramedWrite};
 
 	use super::packets::CodePacket;
 
@@ -568,41 +1596,901 @@ mod rpc {
 		INSTANCE_COUNTER.fetch_add(1, Ordering::SeqCst)
 	}
 
-	struct ChannelClient {}
+	type PendingCalls = Arc<Mutex<HashMap<i32, oneshot::Sender<Result<CodePacket, PromiseErrorData>>>>>;
+	type PendingListens = Arc<Mutex<HashMap<i32, mpsc::UnboundedSender<CodePacket>>>>;
+
+	/// Selects the wire transport a `ChannelClient`/`ChannelServerBuilder`
+	/// drives its reader/writer tasks with.
+	#[derive(Debug, Clone, Copy)]
+	pub enum TransportMode {
+		/// The plain `RpcCodec` encoding, with no length/stream-id framing.
+		/// Kept as the default for compatibility with existing VS Code
+		/// peers that don't speak the framed header.
+		Unframed,
+		/// Each message is wrapped in `requests::write_framed`'s header and
+		/// tagged with `stream_id`. On its own this still needs a private
+		/// reader/writer pair per stream, same as `Unframed`; to actually
+		/// share one socket across several logical streams, drive this mode
+		/// through a shared `FrameRouter`/`FrameMultiplexer` pair instead of
+		/// a raw reader/writer — see `ChannelClient::new_multiplexed` and
+		/// `ChannelServerBuilder::serve_multiplexed`.
+		Framed { stream_id: u32 },
+	}
+
+	impl Default for TransportMode {
+		fn default() -> Self {
+			TransportMode::Unframed
+		}
+	}
+
+	/// Drives the outbound half of a channel: messages sent in over `rx` are
+	/// framed and written out, independently of however fast (or slow) the
+	/// peer is reading from us.
+	async fn run_writer(
+		writer: impl AsyncWrite + Unpin,
+		mut rx: mpsc::UnboundedReceiver<Message>,
+		mode: TransportMode,
+	) {
+		match mode {
+			TransportMode::Unframed => {
+				let mut sink = FramedWrite::new(writer, RpcCodec);
+				while let Some(message) = rx.recv().await {
+					if sink.send(message).await.is_err() {
+						break;
+					}
+				}
+			}
+			TransportMode::Framed { stream_id } => {
+				tokio::pin!(writer);
+				while let Some(message) = rx.recv().await {
+					if requests::write_framed(
+						stream_id,
+						requests::FRAME_TYPE_DATA,
+						0,
+						Some(&message),
+						&mut writer,
+					)
+					.await
+					.is_err()
+					{
+						break;
+					}
+				}
+			}
+		}
+	}
+
+	/// Drives the inbound half of a channel with whichever transport `mode`
+	/// selects, invoking `on_message` for each `Message` that arrives. Stops
+	/// on a transport error, EOF, or (for the framed transport) a frame
+	/// carrying `FLAG_REMOTE_CLOSED`.
+	async fn read_messages(
+		reader: impl AsyncRead + Unpin,
+		mode: TransportMode,
+		mut on_message: impl FnMut(Message),
+	) {
+		match mode {
+			TransportMode::Unframed => {
+				let mut stream = FramedRead::new(reader, RpcCodec);
+				while let Some(message) = stream.next().await {
+					match message {
+						Ok(m) if is_unsupported_streamed_message(&m) => break,
+						Ok(m) => on_message(m),
+						Err(_) => break,
+					}
+				}
+			}
+			TransportMode::Framed { .. } => {
+				tokio::pin!(reader);
+				loop {
+					let frame = match requests::read_framed(&mut reader).await {
+						Ok(f) => f,
+						Err(_) => break,
+					};
+					if let Some(message) = frame.message {
+						if is_unsupported_streamed_message(&message) {
+							break;
+						}
+						on_message(message);
+					}
+					if frame.flags & requests::FLAG_REMOTE_CLOSED != 0 {
+						break;
+					}
+				}
+			}
+		}
+	}
+
+	/// `RequestPromiseStream`/`ResponsePromiseStreamSuccess` are followed on
+	/// the wire by a raw `packets::write_stream_body` payload that isn't
+	/// part of the `CodePacket` framing `read_message`/`try_decode_message`
+	/// understand, and nothing on the shared `ChannelClient`/
+	/// `ChannelServerBuilder` dispatch path drains it yet. Continuing to
+	/// read after one would misparse those stream-body bytes as the next
+	/// message's header, silently corrupting the rest of the connection —
+	/// so every shared read loop treats receiving one the same as a
+	/// transport error and stops instead.
+	fn is_unsupported_streamed_message(message: &Message) -> bool {
+		matches!(
+			message,
+			Message::RequestPromiseStream { .. } | Message::ResponsePromiseStreamSuccess { .. }
+		)
+	}
+
+	/// Demultiplexes frames read from one shared transport by `stream_id`,
+	/// so several `ChannelClient::new_multiplexed`/
+	/// `ChannelServerBuilder::serve_multiplexed` instances can share a
+	/// single socket instead of each needing a private reader. Construct
+	/// one per physical connection with `spawn`, then call `open` once per
+	/// logical stream to get that stream's `Message`s.
+	pub struct FrameRouter {
+		routes: Arc<Mutex<HashMap<u32, mpsc::UnboundedSender<Message>>>>,
+	}
+
+	impl FrameRouter {
+		/// Spawns the task that reads frames off `reader` and fans each one
+		/// out to whichever stream's channel `open` registered. A frame for
+		/// a `stream_id` nobody has `open`ed is silently dropped, since the
+		/// local side isn't listening for that stream.
+		pub fn spawn(reader: impl AsyncRead + Unpin + Send + 'static) -> Self {
+			let routes: Arc<Mutex<HashMap<u32, mpsc::UnboundedSender<Message>>>> =
+				Arc::new(Mutex::new(HashMap::new()));
+
+			let routes_task = routes.clone();
+			tokio::spawn(async move {
+				tokio::pin!(reader);
+				loop {
+					let frame = match requests::read_framed(&mut reader).await {
+						Ok(f) => f,
+						Err(_) => break,
+					};
+					if let Some(message) = &frame.message {
+						if is_unsupported_streamed_message(message) {
+							break;
+						}
+					}
+					if let Some(tx) = routes_task.lock().unwrap().get(&frame.stream_id) {
+						if let Some(message) = frame.message {
+							let _ = tx.send(message);
+						}
+					}
+					if frame.flags & requests::FLAG_REMOTE_CLOSED != 0 {
+						routes_task.lock().unwrap().remove(&frame.stream_id);
+					}
+				}
+				// the read loop only exits on EOF, a transport error, or an
+				// unsupported streamed message, so every route still
+				// registered is now orphaned. Drop them
+				// all so their `run_reader_multiplexed`/dispatch loops see
+				// a closed channel and clean up, instead of waiting
+				// forever for frames that will never arrive.
+				routes_task.lock().unwrap().clear();
+			});
+
+			Self { routes }
+		}
+
+		/// Registers `stream_id` as a logical stream this side wants to
+		/// receive, returning the `Message`s the router will dispatch to
+		/// it. Only one receiver may be open per `stream_id` at a time;
+		/// opening the same `stream_id` again replaces the previous route.
+		pub fn open(&self, stream_id: u32) -> mpsc::UnboundedReceiver<Message> {
+			let (tx, rx) = mpsc::unbounded_channel();
+			self.routes.lock().unwrap().insert(stream_id, tx);
+			rx
+		}
+	}
 
-	struct ChannelServerBuilder {
-		channels: HashMap<String, Box<dyn ChannelServerImpl>>,
-		pending: HashMap<i32, oneshot::Sender<CodePacket>>,
+	/// Multiplexes several logical streams' outgoing messages onto one
+	/// shared transport, tagging each with its `stream_id` through
+	/// `requests::write_framed`. Construct one per physical connection with
+	/// `spawn`, then call `drain_into` once per logical stream to forward
+	/// that stream's outbound messages through it. Cheap to `clone`: every
+	/// clone shares the same underlying writer task.
+	#[derive(Clone)]
+	pub struct FrameMultiplexer {
+		outbound: mpsc::UnboundedSender<(u32, Message)>,
+	}
+
+	impl FrameMultiplexer {
+		/// Spawns the task that writes every `(stream_id, Message)` sent
+		/// through it out to `writer` as a `write_framed` frame.
+		pub fn spawn(writer: impl AsyncWrite + Unpin + Send + 'static) -> Self {
+			let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<(u32, Message)>();
+
+			tokio::spawn(async move {
+				tokio::pin!(writer);
+				while let Some((stream_id, message)) = outbound_rx.recv().await {
+					if requests::write_framed(
+						stream_id,
+						requests::FRAME_TYPE_DATA,
+						0,
+						Some(&message),
+						&mut writer,
+					)
+					.await
+					.is_err()
+					{
+						break;
+					}
+				}
+			});
+
+			Self { outbound }
+		}
+
+		/// Tags every message taken off `rx` with `stream_id` and forwards
+		/// it to the shared writer task, until `rx` closes or the writer
+		/// task has already exited. Takes `self` by value so it can be
+		/// driven as its own `tokio::spawn`ed task; `clone()` a
+		/// `FrameMultiplexer` before calling this if you still need it
+		/// afterwards.
+		async fn drain_into(self, stream_id: u32, mut rx: mpsc::UnboundedReceiver<Message>) {
+			while let Some(message) = rx.recv().await {
+				if self.outbound.send((stream_id, message)).is_err() {
+					break;
+				}
+			}
+		}
+	}
+
+	/// A client-side handle to a single channel transport. Multiple logical
+	/// channels/methods can be multiplexed over the same underlying
+	/// `AsyncRead`/`AsyncWrite` pair through one `ChannelClient`.
+	pub struct ChannelClient {
+		outbound: mpsc::UnboundedSender<Message>,
+		pending_calls: PendingCalls,
+		pending_listens: PendingListens,
+	}
+
+	impl ChannelClient {
+		/// Spawns the reader and writer tasks and returns a handle that can
+		/// issue `call`s and `listen`s over them.
+		pub fn new(
+			reader: impl AsyncRead + Unpin + Send + 'static,
+			writer: impl AsyncWrite + Unpin + Send + 'static,
+		) -> Self {
+			Self::new_with_mode(reader, writer, TransportMode::default())
+		}
+
+		/// Like `new`, but selects the wire transport explicitly. The reader
+		/// and writer stay private to this client in either mode; use
+		/// `new_multiplexed` to share one socket with other streams.
+		pub fn new_with_mode(
+			reader: impl AsyncRead + Unpin + Send + 'static,
+			writer: impl AsyncWrite + Unpin + Send + 'static,
+			mode: TransportMode,
+		) -> Self {
+			let (outbound, outbound_rx) = mpsc::unbounded_channel();
+			let pending_calls: PendingCalls = Arc::new(Mutex::new(HashMap::new()));
+			let pending_listens: PendingListens = Arc::new(Mutex::new(HashMap::new()));
+
+			tokio::spawn(run_writer(writer, outbound_rx, mode));
+			tokio::spawn(Self::run_reader(
+				reader,
+				mode,
+				pending_calls.clone(),
+				pending_listens.clone(),
+			));
+
+			Self {
+				outbound,
+				pending_calls,
+				pending_listens,
+			}
+		}
+
+		/// Like `new_with_mode` with `TransportMode::Framed`, but shares one
+		/// physical transport across several logical streams through a
+		/// `FrameRouter`/`FrameMultiplexer` pair instead of requiring a
+		/// private reader/writer pair per stream.
+		pub fn new_multiplexed(router: &FrameRouter, mux: &FrameMultiplexer, stream_id: u32) -> Self {
+			let pending_calls: PendingCalls = Arc::new(Mutex::new(HashMap::new()));
+			let pending_listens: PendingListens = Arc::new(Mutex::new(HashMap::new()));
+
+			let (outbound, outbound_rx) = mpsc::unbounded_channel();
+			tokio::spawn(mux.clone().drain_into(stream_id, outbound_rx));
+
+			let inbound = router.open(stream_id);
+			tokio::spawn(Self::run_reader_multiplexed(
+				inbound,
+				pending_calls.clone(),
+				pending_listens.clone(),
+			));
+
+			Self {
+				outbound,
+				pending_calls,
+				pending_listens,
+			}
+		}
+
+		/// Applies one inbound `Message` to a client's promise/event-listen
+		/// dispatch tables. Shared by `run_reader` (single-stream transports)
+		/// and `run_reader_multiplexed` (streams sharing a socket through a
+		/// `FrameRouter`), so the two code paths can't drift apart.
+		fn dispatch_message(message: Message, pending_calls: &PendingCalls, pending_listens: &PendingListens) {
+			match message {
+				Message::ResponsePromiseSuccess { id } => {
+					if let Some(tx) = pending_calls.lock().unwrap().remove(&id) {
+						let _ = tx.send(Ok(CodePacket::Undefined));
+					}
+				}
+				Message::ResponsePromiseError { id, data } => {
+					if let Some(tx) = pending_calls.lock().unwrap().remove(&id) {
+						let _ = tx.send(Err(data));
+					}
+				}
+				Message::ResponsePromiseErrorObject { id, data } => {
+					if let Some(tx) = pending_calls.lock().unwrap().remove(&id) {
+						let data = data.to_object().unwrap_or_else(|_| PromiseErrorData {
+							message: "unknown error".to_string(),
+							name: "Error".to_string(),
+							stack: None,
+						});
+						let _ = tx.send(Err(data));
+					}
+				}
+				Message::ResponseEventFired { id, data } => {
+					if let Some(tx) = pending_listens.lock().unwrap().get(&id) {
+						let _ = tx.send(data);
+					}
+				}
+				Message::ResponseEventError { id, .. } => {
+					// dropping the sender ends the subscriber's `EventStream`
+					// now instead of leaving it registered forever,
+					// indistinguishable from a valid but quiet subscription.
+					pending_listens.lock().unwrap().remove(&id);
+				}
+				// requests and ResponseInitialize are not expected on the
+				// client's read half of a single-direction channel.
+				_ => {}
+			}
+		}
+
+		/// The read loop only returns once nothing more will ever answer the
+		/// calls/listens still outstanding (EOF, a transport error, or — for
+		/// a multiplexed stream — the router closing its route). Reject
+		/// them instead of leaving their futures/streams hanging forever
+		/// and their entries leaked in the pending maps.
+		fn reject_pending(pending_calls: &PendingCalls, pending_listens: &PendingListens) {
+			for (_, tx) in pending_calls.lock().unwrap().drain() {
+				let _ = tx.send(Err(PromiseErrorData {
+					message: "connection closed".to_string(),
+					name: "Error".to_string(),
+					stack: None,
+				}));
+			}
+			pending_listens.lock().unwrap().clear();
+		}
+
+		async fn run_reader(
+			reader: impl AsyncRead + Unpin,
+			mode: TransportMode,
+			pending_calls: PendingCalls,
+			pending_listens: PendingListens,
+		) {
+			read_messages(reader, mode, |message| {
+				Self::dispatch_message(message, &pending_calls, &pending_listens)
+			})
+			.await;
+			Self::reject_pending(&pending_calls, &pending_listens);
+		}
+
+		/// Like `run_reader`, but consumes `Message`s a `FrameRouter` has
+		/// already demultiplexed for this stream, instead of reading frames
+		/// off a private transport itself.
+		async fn run_reader_multiplexed(
+			mut inbound: mpsc::UnboundedReceiver<Message>,
+			pending_calls: PendingCalls,
+			pending_listens: PendingListens,
+		) {
+			while let Some(message) = inbound.recv().await {
+				Self::dispatch_message(message, &pending_calls, &pending_listens);
+			}
+			Self::reject_pending(&pending_calls, &pending_listens);
+		}
+
+		/// Calls `method` on `channel` with `arg`, returning a `PendingCall`
+		/// that resolves with the response once the server's
+		/// `ResponsePromiseSuccess`, `ResponsePromiseError`, or
+		/// `ResponsePromiseErrorObject` arrives. Call `.cancel()` on it to
+		/// unblock the call early instead of awaiting the response.
+		pub fn call(&self, channel: &str, method: &str, arg: CodePacket) -> PendingCall {
+			let id = next_counter();
+			let (tx, rx) = oneshot::channel();
+			self.pending_calls.lock().unwrap().insert(id, tx);
+
+			let sent = self.outbound.send(Message::RequestPromise {
+				id,
+				channel_name: channel.to_string(),
+				name: method.to_string(),
+				arg,
+			});
+			if sent.is_err() {
+				// the writer task has already exited, so this request will
+				// never reach the peer and nothing will ever remove `id`
+				// from `pending_calls`. Resolve it eagerly instead of
+				// registering a promise that can never resolve.
+				if let Some(tx) = self.pending_calls.lock().unwrap().remove(&id) {
+					let _ = tx.send(Err(PromiseErrorData {
+						message: "connection closed".to_string(),
+						name: "Error".to_string(),
+						stack: None,
+					}));
+				}
+			}
+
+			PendingCall {
+				id,
+				rx,
+				outbound: self.outbound.clone(),
+				pending_calls: self.pending_calls.clone(),
+			}
+		}
+
+		/// Subscribes to an event on `channel`, returning a `Stream` of the
+		/// values the server fires. Dropping the stream disposes the
+		/// subscription on the server.
+		pub fn listen(&self, channel: &str, method: &str, arg: CodePacket) -> EventStream {
+			let id = next_counter();
+			let (tx, rx) = mpsc::unbounded_channel();
+			self.pending_listens.lock().unwrap().insert(id, tx);
+
+			let sent = self.outbound.send(Message::RequestEventListen {
+				id,
+				channel_name: channel.to_string(),
+				name: method.to_string(),
+				arg,
+			});
+			if sent.is_err() {
+				// the writer task has already exited, so this subscription
+				// will never reach the peer. Drop our sender so the
+				// returned stream ends immediately instead of waiting
+				// forever for events that can never arrive.
+				self.pending_listens.lock().unwrap().remove(&id);
+			}
+
+			EventStream {
+				id,
+				rx,
+				outbound: self.outbound.clone(),
+				pending_listens: self.pending_listens.clone(),
+			}
+		}
+	}
+
+	/// A `Future` resolving to the response of a single `ChannelClient::call`.
+	/// Exposes the `id` it was registered under so the caller can `.cancel()`
+	/// it, unlike a bare `RequestPromiseCancel` message, which only notifies
+	/// the peer and leaves the local future waiting for a response that may
+	/// never come.
+	pub struct PendingCall {
+		id: i32,
+		rx: oneshot::Receiver<Result<CodePacket, PromiseErrorData>>,
+		outbound: mpsc::UnboundedSender<Message>,
+		pending_calls: PendingCalls,
+	}
+
+	impl PendingCall {
+		/// The id this call was registered under, e.g. to correlate it with
+		/// out-of-band server logging.
+		pub fn id(&self) -> i32 {
+			self.id
+		}
+
+		/// Requests cancellation: tells the peer via `RequestPromiseCancel`
+		/// and, unlike that message on its own, immediately removes this
+		/// call's entry from `pending_calls` and resolves it with an error,
+		/// so awaiting it afterwards (or concurrently) doesn't hang waiting
+		/// for a response the peer may never send.
+		pub fn cancel(&self) {
+			let _ = self.outbound.send(Message::RequestPromiseCancel { id: self.id });
+			if let Some(tx) = self.pending_calls.lock().unwrap().remove(&self.id) {
+				let _ = tx.send(Err(PromiseErrorData {
+					message: "call was cancelled".to_string(),
+					name: "Error".to_string(),
+					stack: None,
+				}));
+			}
+		}
+	}
+
+	impl Future for PendingCall {
+		type Output = Result<CodePacket, PromiseErrorData>;
+
+		fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+			Pin::new(&mut self.rx).poll(cx).map(|r| {
+				r.unwrap_or_else(|_| {
+					Err(PromiseErrorData {
+						message: "channel closed before a response arrived".to_string(),
+						name: "Error".to_string(),
+						stack: None,
+					})
+				})
+			})
+		}
+	}
+
+	/// A `Stream` of events from a single `ChannelClient::listen` call. On
+	/// drop, it removes itself from the client's dispatch table and tells
+	/// the server to stop firing events for it.
+	pub struct EventStream {
+		id: i32,
+		rx: mpsc::UnboundedReceiver<CodePacket>,
+		outbound: mpsc::UnboundedSender<Message>,
+		pending_listens: PendingListens,
+	}
+
+	impl Stream for EventStream {
+		type Item = CodePacket;
+
+		fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+			self.rx.poll_recv(cx)
+		}
+	}
+
+	impl Drop for EventStream {
+		fn drop(&mut self) {
+			self.pending_listens.lock().unwrap().remove(&self.id);
+			let _ = self.outbound.send(Message::RequestEventDispose { id: self.id });
+		}
+	}
+
+	/// The server side of a single channel, exposed by name through
+	/// `ChannelServerBuilder`. Implementations dispatch `call`/`listen` by
+	/// method name however suits them (a big match, a sub-registry, etc).
+	#[async_trait]
+	pub trait ChannelServerImpl: Send + Sync {
+		async fn call(&self, method: String, arg: CodePacket) -> Result<CodePacket, PromiseErrorData>;
+
+		fn listen(&self, method: String, arg: CodePacket) -> Pin<Box<dyn Stream<Item = CodePacket> + Send>>;
+	}
+
+	/// Builds the set of named channels served over one transport, then
+	/// drives the request/response and event-listen loop for it.
+	pub struct ChannelServerBuilder {
+		channels: HashMap<String, Arc<dyn ChannelServerImpl>>,
 	}
 
 	impl ChannelServerBuilder {
 		pub fn new() -> Self {
 			Self {
 				channels: HashMap::new(),
-				pending: HashMap::new(),
 			}
 		}
 
 		pub fn register_channel(
 			&mut self,
 			name: &str,
-			channel: impl ChannelServerImpl + Sized + 'static,
+			channel: impl ChannelServerImpl + 'static,
 		) {
-			self.channels.insert(name.to_string(), Box::new(channel));
+			self.channels.insert(name.to_string(), Arc::new(channel));
 		}
 
-		pub async fn serve(self, reader: impl AsyncRead + Unpin, writer: impl AsyncWrite) {
-			let mut reader = BufReader::new(reader);
-			let mut writer = BufWriter::new(writer);
+		/// Reads requests off `reader` and dispatches them to the registered
+		/// channels, writing responses and fired events back out on
+		/// `writer`. The two halves run as independent tasks so a slow
+		/// client write never stalls request processing, and vice versa.
+		/// Uses the legacy unframed transport; see `serve_with_mode` to pick
+		/// the framed one.
+		pub async fn serve(
+			self,
+			reader: impl AsyncRead + Unpin + Send + 'static,
+			writer: impl AsyncWrite + Unpin + Send + 'static,
+		) {
+			self.serve_with_mode(reader, writer, TransportMode::default())
+				.await
+		}
 
-			loop {
-				let msg = requests::read_message(&mut reader).await.unwrap();
+		/// Like `serve`, but selects the wire transport explicitly.
+		pub async fn serve_with_mode(
+			self,
+			reader: impl AsyncRead + Unpin + Send + 'static,
+			writer: impl AsyncWrite + Unpin + Send + 'static,
+			mode: TransportMode,
+		) {
+			let (outbound, outbound_rx) = mpsc::unbounded_channel();
+			tokio::spawn(run_writer(writer, outbound_rx, mode));
+
+			let channels = Arc::new(self.channels);
+			let active_calls: Arc<Mutex<HashMap<i32, AbortHandle>>> = Arc::new(Mutex::new(HashMap::new()));
+			let active_listens: Arc<Mutex<HashMap<i32, AbortHandle>>> = Arc::new(Mutex::new(HashMap::new()));
+
+			read_messages(reader, mode, |message| {
+				Self::dispatch_message(message, &channels, &outbound, &active_calls, &active_listens)
+			})
+			.await
+		}
+
+		/// Like `serve_with_mode` with `TransportMode::Framed`, but shares
+		/// one physical transport across several logical streams through a
+		/// `FrameRouter`/`FrameMultiplexer` pair instead of requiring a
+		/// private reader/writer pair per stream. The route is opened before
+		/// this returns, so the returned future can be spawned and messages
+		/// for `stream_id` that arrive before it is first polled are kept.
+		pub fn serve_multiplexed(
+			self,
+			router: &FrameRouter,
+			mux: &FrameMultiplexer,
+			stream_id: u32,
+		) -> impl Future<Output = ()> + Send + 'static {
+			let (outbound, outbound_rx) = mpsc::unbounded_channel();
+			tokio::spawn(mux.clone().drain_into(stream_id, outbound_rx));
+
+			let channels = Arc::new(self.channels);
+			let active_calls: Arc<Mutex<HashMap<i32, AbortHandle>>> = Arc::new(Mutex::new(HashMap::new()));
+			let active_listens: Arc<Mutex<HashMap<i32, AbortHandle>>> = Arc::new(Mutex::new(HashMap::new()));
+
+			let mut inbound = router.open(stream_id);
+			async move {
+				while let Some(message) = inbound.recv().await {
+					Self::dispatch_message(message, &channels, &outbound, &active_calls, &active_listens);
+				}
+			}
+		}
+
+		/// Applies one inbound `Message` to the registered channels, driving
+		/// calls/listens and writing responses/events back out on
+		/// `outbound`. Shared by `serve_with_mode` (single-stream
+		/// transports) and `serve_multiplexed` (streams sharing a socket
+		/// through a `FrameRouter`), so the two code paths can't drift
+		/// apart.
+		fn dispatch_message(
+			message: Message,
+			channels: &Arc<HashMap<String, Arc<dyn ChannelServerImpl>>>,
+			outbound: &mpsc::UnboundedSender<Message>,
+			active_calls: &Arc<Mutex<HashMap<i32, AbortHandle>>>,
+			active_listens: &Arc<Mutex<HashMap<i32, AbortHandle>>>,
+		) {
+			match message {
+				Message::RequestPromise {
+					id,
+					channel_name,
+					name,
+					arg,
+				} => {
+					let Some(channel) = channels.get(&channel_name).cloned() else {
+						let _ = outbound.send(Message::ResponsePromiseError {
+							id,
+							data: PromiseErrorData {
+								message: format!("unknown channel '{}'", channel_name),
+								name: "Error".to_string(),
+								stack: None,
+							},
+						});
+						return;
+					};
+
+					let outbound = outbound.clone();
+					let active_calls_done = active_calls.clone();
+					let handle = tokio::spawn(async move {
+						let response = match channel.call(name, arg).await {
+							// ResponsePromiseSuccess carries no payload in
+							// this protocol revision, so a successful
+							// result is acknowledged without its data.
+							Ok(_) => Message::ResponsePromiseSuccess { id },
+							Err(data) => Message::ResponsePromiseError { id, data },
+						};
+						let _ = outbound.send(response);
+						active_calls_done.lock().unwrap().remove(&id);
+					});
+					active_calls.lock().unwrap().insert(id, handle.abort_handle());
+				}
+				Message::RequestPromiseCancel { id } => {
+					if let Some(handle) = active_calls.lock().unwrap().remove(&id) {
+						handle.abort();
+					}
+				}
+				Message::RequestEventListen {
+					id,
+					channel_name,
+					name,
+					arg,
+				} => {
+					let Some(channel) = channels.get(&channel_name).cloned() else {
+						let _ = outbound.send(Message::ResponseEventError {
+							id,
+							data: PromiseErrorData {
+								message: format!("unknown channel '{}'", channel_name),
+								name: "Error".to_string(),
+								stack: None,
+							},
+						});
+						return;
+					};
+
+					let mut events = channel.listen(name, arg);
+					let outbound = outbound.clone();
+					let handle = tokio::spawn(async move {
+						while let Some(data) = events.next().await {
+							if outbound
+								.send(Message::ResponseEventFired { id, data })
+								.is_err()
+							{
+								break;
+							}
+						}
+					});
+					active_listens.lock().unwrap().insert(id, handle.abort_handle());
+				}
+				Message::RequestEventDispose { id } => {
+					if let Some(handle) = active_listens.lock().unwrap().remove(&id) {
+						handle.abort();
+					}
+				}
+				// responses are not expected on the server's read half.
+				_ => {}
 			}
 		}
 	}
 
-	#[async_trait]
-	pub trait ChannelServerImpl {
-		fn handle_call(&self, method: String, arg: CodePacket) -> CodePacket;
+	mod tests {
+		use super::*;
+
+		struct EchoChannel;
+
+		#[async_trait]
+		impl ChannelServerImpl for EchoChannel {
+			async fn call(&self, _method: String, arg: CodePacket) -> Result<CodePacket, PromiseErrorData> {
+				Ok(arg)
+			}
+
+			fn listen(
+				&self,
+				_method: String,
+				_arg: CodePacket,
+			) -> Pin<Box<dyn Stream<Item = CodePacket> + Send>> {
+				Box::pin(futures::stream::empty())
+			}
+		}
+
+		#[tokio::test]
+		async fn test_framed_transport_round_trips_a_call() {
+			let (client_write_end, server_read_end) = tokio::io::duplex(8192);
+			let (server_write_end, client_read_end) = tokio::io::duplex(8192);
+
+			let (_, client_writer) = tokio::io::split(client_write_end);
+			let (server_reader, _) = tokio::io::split(server_read_end);
+			let (_, server_writer) = tokio::io::split(server_write_end);
+			let (client_reader, _) = tokio::io::split(client_read_end);
+
+			let mut builder = ChannelServerBuilder::new();
+			builder.register_channel("echo", EchoChannel);
+			tokio::spawn(builder.serve_with_mode(
+				server_reader,
+				server_writer,
+				TransportMode::Framed { stream_id: 1 },
+			));
+
+			let client = ChannelClient::new_with_mode(
+				client_reader,
+				client_writer,
+				TransportMode::Framed { stream_id: 1 },
+			);
+
+			let result = client
+				.call("echo", "anything", CodePacket::from_string("hi".to_string()))
+				.await;
+			assert_eq!(result, Ok(CodePacket::Undefined));
+		}
+
+		#[tokio::test]
+		async fn test_listen_on_an_unknown_channel_closes_the_stream_instead_of_hanging() {
+			let (client_write_end, server_read_end) = tokio::io::duplex(8192);
+			let (server_write_end, client_read_end) = tokio::io::duplex(8192);
+
+			let (_, client_writer) = tokio::io::split(client_write_end);
+			let (server_reader, _) = tokio::io::split(server_read_end);
+			let (_, server_writer) = tokio::io::split(server_write_end);
+			let (client_reader, _) = tokio::io::split(client_read_end);
+
+			let mut builder = ChannelServerBuilder::new();
+			builder.register_channel("echo", EchoChannel);
+			tokio::spawn(builder.serve_with_mode(
+				server_reader,
+				server_writer,
+				TransportMode::Framed { stream_id: 1 },
+			));
+
+			let client = ChannelClient::new_with_mode(
+				client_reader,
+				client_writer,
+				TransportMode::Framed { stream_id: 1 },
+			);
+
+			// "unregistered" is not a channel the server knows about, so
+			// the stream should close right away instead of staying
+			// registered forever indistinguishable from a valid but
+			// quiet subscription.
+			let mut events = client.listen("unregistered", "anything", CodePacket::Undefined);
+			assert_eq!(events.next().await, None);
+		}
+
+		#[tokio::test]
+		async fn test_call_rejects_pending_promise_when_the_connection_closes() {
+			let (client_writer, _unused_peer) = tokio::io::duplex(8192);
+			let (peer_writer, client_reader) = tokio::io::duplex(8192);
+
+			let client = ChannelClient::new(client_reader, client_writer);
+
+			let call = client.call("echo", "anything", CodePacket::from_string("hi".to_string()));
+
+			// closing the peer's write half sends EOF to the client's
+			// reader, ending its read loop with no response ever arriving.
+			drop(peer_writer);
+
+			assert!(call.await.is_err());
+		}
+
+		#[tokio::test]
+		async fn test_cancel_unblocks_the_call_without_closing_the_connection() {
+			let (client_writer, _unused_peer) = tokio::io::duplex(8192);
+			let (_peer_writer, client_reader) = tokio::io::duplex(8192);
+
+			let client = ChannelClient::new(client_reader, client_writer);
+
+			let call = client.call("echo", "anything", CodePacket::from_string("hi".to_string()));
+			call.cancel();
+
+			// cancelling resolves the call immediately, without the
+			// connection closing or a response ever arriving.
+			assert!(call.await.is_err());
+		}
+
+		#[tokio::test]
+		async fn test_receiving_an_unwired_stream_message_closes_the_read_loop_instead_of_desyncing() {
+			let (client_writer, _unused_peer) = tokio::io::duplex(8192);
+			let (mut peer_writer, client_reader) = tokio::io::duplex(8192);
+
+			let client = ChannelClient::new(client_reader, client_writer);
+			let call = client.call("echo", "anything", CodePacket::from_string("hi".to_string()));
+
+			requests::write_message(&Message::ResponsePromiseStreamSuccess { id: 0 }, &mut peer_writer)
+				.await
+				.unwrap();
+
+			// the client's read loop stops instead of misparsing the
+			// never-sent stream body as the next message, so the
+			// still-pending call is rejected the same way a closed
+			// connection would reject it.
+			assert!(call.await.is_err());
+		}
+
+		#[tokio::test]
+		async fn test_multiplexed_streams_share_one_physical_connection() {
+			let (client_write_end, server_read_end) = tokio::io::duplex(8192);
+			let (server_write_end, client_read_end) = tokio::io::duplex(8192);
+
+			let (_, client_writer) = tokio::io::split(client_write_end);
+			let (server_reader, _) = tokio::io::split(server_read_end);
+			let (_, server_writer) = tokio::io::split(server_write_end);
+			let (client_reader, _) = tokio::io::split(client_read_end);
+
+			// One physical connection, two logical channels (stream 1 and
+			// stream 2) multiplexed over it.
+			let server_router = FrameRouter::spawn(server_reader);
+			let server_mux = FrameMultiplexer::spawn(server_writer);
+			let client_router = FrameRouter::spawn(client_reader);
+			let client_mux = FrameMultiplexer::spawn(client_writer);
+
+			let mut echo_builder = ChannelServerBuilder::new();
+			echo_builder.register_channel("echo", EchoChannel);
+			tokio::spawn(echo_builder.serve_multiplexed(&server_router, &server_mux, 1));
+
+			let mut other_builder = ChannelServerBuilder::new();
+			other_builder.register_channel("echo", EchoChannel);
+			tokio::spawn(other_builder.serve_multiplexed(&server_router, &server_mux, 2));
+
+			let stream1 = ChannelClient::new_multiplexed(&client_router, &client_mux, 1);
+			let stream2 = ChannelClient::new_multiplexed(&client_router, &client_mux, 2);
+
+			let (result1, result2) = tokio::join!(
+				stream1.call("echo", "anything", CodePacket::from_string("one".to_string())),
+				stream2.call("echo", "anything", CodePacket::from_string("two".to_string())),
+			);
+			assert_eq!(result1, Ok(CodePacket::Undefined));
+			assert_eq!(result2, Ok(CodePacket::Undefined));
+		}
 	}
 }